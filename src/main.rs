@@ -1,56 +1,250 @@
 //! Rust implementation of uptime
+mod sparkline;
+mod utmp;
+
 use chrono::prelude::*;
 use chrono::Duration;
-use std::{env, error::Error, fmt, fs, io::Read};
-
-const UTMP_SIZE: usize = 384;
+use serde::Serialize;
+use std::{
+    env,
+    error::Error,
+    fmt, fs,
+    io::{self, Read, Write},
+    thread,
+    time::Duration as StdDuration,
+};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
-    if args.len() > 2 {
-        usage();
-        return Err(Box::new(ArgsError));
+
+    let Args {
+        option,
+        file: file_arg,
+        watch_interval,
+    } = parse_args(&args[1..])?;
+
+    if option.as_deref() == Some("--watch") {
+        return watch_loop(&file_arg, watch_interval.unwrap_or(2));
+    }
+
+    match option.as_deref() {
+        None => {
+            let stats = collect_stats(&file_arg)?;
+            let current_time_str = stats.time.format("%H:%M:%S").to_string();
+            let uptime_str = build_uptime_string(&stats.uptime, UptimeFormat::Normal);
+
+            println!(
+                " {} up {}, {}, load average: {}",
+                current_time_str,
+                uptime_str,
+                format_users(stats.users),
+                format_loadavg(&stats.loadavg)
+            );
+        }
+        Some("-p") | Some("--pretty") => {
+            let (_, uptime) = collect_uptime(&file_arg)?;
+            println!("up {}", build_uptime_string(&uptime, UptimeFormat::Pretty));
+        }
+        Some("-h") | Some("--help") => usage(),
+        Some("-s") | Some("--since") => {
+            let (time, uptime) = collect_uptime(&file_arg)?;
+            let since_datetime = time - uptime;
+            println!("{}", since_datetime.format("%Y-%m-%d %H:%M:%S").to_string())
+        }
+        Some("-V") | Some("--version") => println!("ruptime 0.1.0"),
+        Some("-i") | Some("--idle") => {
+            let stats = collect_stats(&file_arg)?;
+            let idle = stats.idle.ok_or(IdleError)?;
+            let num_cpus = get_num_cpus(fs::read_to_string("/proc/cpuinfo")?).max(1);
+            let idle_str = build_uptime_string(&idle, UptimeFormat::Pretty);
+            let avg_idle = idle.num_milliseconds() as f64
+                / (stats.uptime.num_milliseconds() as f64 * num_cpus as f64)
+                * 100.0;
+
+            println!("idle {} ({:.2}% average)", idle_str, avg_idle);
+        }
+        Some("--json") => {
+            let stats = collect_stats(&file_arg)?;
+            println!("{}", serde_json::to_string(&SystemStatsJson::from(&stats))?);
+        }
+        Some(_) => {
+            usage();
+            return Err(Box::new(ArgsError));
+        }
     }
-    let arg = args.get(1);
 
-    let local: DateTime<Local> = Local::now();
-    let uptime = get_uptime(fs::read_to_string("/proc/uptime")?)?;
+    Ok(())
+}
+
+/// All the data needed to produce any of ruptime's output formats, gathered
+/// once so the human-readable and JSON printers stay in sync
+struct SystemStats {
+    time: DateTime<Local>,
+    uptime: Duration,
+    idle: Option<Duration>,
+    loadavg: [f64; 3],
+    users: usize,
+}
+
+/// Gathers just the current time and uptime, without touching
+/// /proc/loadavg or the user-login data, for display modes that don't need
+/// them (`-p`/`--pretty`, `-s`/`--since`)
+fn collect_uptime(
+    file_arg: &Option<String>,
+) -> Result<(DateTime<Local>, Duration), Box<dyn Error>> {
+    let time: DateTime<Local> = Local::now();
 
-    match arg {
+    let uptime = match file_arg {
+        Some(path) => {
+            let mut buf: Vec<u8> = Vec::new();
+            let mut f = fs::File::open(path)?;
+            f.read_to_end(&mut buf)?;
+            let records = utmp::parse(&buf);
+
+            let boottime = utmp::boot_time(&records).ok_or(UtmpError)?;
+            time.signed_duration_since(boottime)
+        }
+        None => get_uptime(fs::read_to_string("/proc/uptime")?)?.0,
+    };
+
+    Ok((time, uptime))
+}
+
+/// Gathers the current time, uptime, idle time, load average and user count.
+/// Uptime and user count come either from the live system or from a supplied
+/// utmp/wtmp file; the load average is always read from /proc/loadavg, and
+/// idle time is only known for the live system (`None` when reading a file)
+fn collect_stats(file_arg: &Option<String>) -> Result<SystemStats, Box<dyn Error>> {
+    let time: DateTime<Local> = Local::now();
+    let loadavg = parse_loadavg(fs::read_to_string("/proc/loadavg")?)?;
+
+    let (uptime, idle, users) = match file_arg {
+        Some(path) => {
+            let mut buf: Vec<u8> = Vec::new();
+            let mut f = fs::File::open(path)?;
+            f.read_to_end(&mut buf)?;
+            let records = utmp::parse(&buf);
+
+            let boottime = utmp::boot_time(&records).ok_or(UtmpError)?;
+            (
+                time.signed_duration_since(boottime),
+                None,
+                utmp::count_users(&records),
+            )
+        }
         None => {
-            let current_time_str = local.format("%H:%M:%S").to_string();
-            let uptime_str = build_uptime_string(&uptime, UptimeFormat::Normal);
-            let loadavg_str = get_loadavg(fs::read_to_string("/proc/loadavg")?);
+            let (uptime, idle) = get_uptime(fs::read_to_string("/proc/uptime")?)?;
 
             let mut buf: Vec<u8> = Vec::new();
             let mut f = fs::File::open("/var/run/utmp")?;
             f.read_to_end(&mut buf)?;
-            let no_users = get_no_users(&buf);
+            (uptime, Some(idle), utmp::count_users(&utmp::parse(&buf)))
+        }
+    };
 
-            println!(
-                " {} up {}, {}, load average: {}",
-                current_time_str, uptime_str, no_users, loadavg_str
-            );
+    Ok(SystemStats {
+        time,
+        uptime,
+        idle,
+        loadavg,
+        users,
+    })
+}
+
+/// Shape serialized by `--json`, meant for consumption by monitoring tooling
+#[derive(Serialize)]
+struct SystemStatsJson {
+    time: String,
+    uptime_seconds: i64,
+    uptime_pretty: String,
+    loadavg: [f64; 3],
+    users: usize,
+}
+
+impl From<&SystemStats> for SystemStatsJson {
+    fn from(stats: &SystemStats) -> Self {
+        SystemStatsJson {
+            time: stats.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            uptime_seconds: stats.uptime.num_seconds(),
+            uptime_pretty: build_uptime_string(&stats.uptime, UptimeFormat::Pretty),
+            loadavg: stats.loadavg,
+            users: stats.users,
         }
-        Some(option) => match option.as_str() {
-            "-p" | "--pretty" => {
-                let uptime_str = build_uptime_string(&uptime, UptimeFormat::Pretty);
-                println!("up {}", uptime_str);
-            }
-            "-h" | "--help" => usage(),
-            "-s" | "--since" => {
-                let since_datetime = local - uptime;
-                println!("{}", since_datetime.format("%Y-%m-%d %H:%M:%S").to_string())
+    }
+}
+
+/// The parsed command line: the requested display flag, an optional
+/// utmp/wtmp file to read instead of the live system state, and the
+/// refresh interval for `--watch`
+struct Args {
+    option: Option<String>,
+    file: Option<String>,
+    watch_interval: Option<u64>,
+}
+
+/// Splits the raw argument list into an `Args`. The file can be given
+/// either positionally (`ruptime FILE`) or via `--file FILE`; `--watch` can
+/// optionally be followed by a refresh interval in seconds.
+fn parse_args(args: &[String]) -> Result<Args, ArgsError> {
+    let mut option = None;
+    let mut file = None;
+    let mut watch_interval = None;
+
+    let mut iter = args.iter().peekable();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--file" => file = Some(iter.next().ok_or(ArgsError)?.clone()),
+            "--watch" => {
+                if option.is_some() {
+                    return Err(ArgsError);
+                }
+                option = Some(arg.clone());
+
+                if let Some(seconds) = iter.peek().and_then(|next| next.parse::<u64>().ok()) {
+                    watch_interval = Some(seconds);
+                    iter.next();
+                }
             }
-            "-V" | "--version" => println!("ruptime 0.1.0"),
-            _ => {
-                usage();
-                return Err(Box::new(ArgsError));
+            "-p" | "--pretty" | "-h" | "--help" | "-s" | "--since" | "-V" | "--version" | "-i"
+            | "--idle" | "--json" => {
+                if option.is_some() {
+                    return Err(ArgsError);
+                }
+                option = Some(arg.clone());
             }
-        },
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => return Err(ArgsError),
+        }
     }
 
-    Ok(())
+    Ok(Args {
+        option,
+        file,
+        watch_interval,
+    })
+}
+
+/// Runs `--watch` mode: re-samples stats on an interval and redraws a
+/// single status line with a trailing load-average sparkline
+fn watch_loop(file_arg: &Option<String>, interval_secs: u64) -> Result<(), Box<dyn Error>> {
+    let mut history = sparkline::LoadHistory::new(32);
+
+    loop {
+        let stats = collect_stats(file_arg)?;
+        history.push(stats.loadavg[0]);
+
+        print!(
+            "\r\x1b[K {} up {}, {}, load average: {} {}",
+            stats.time.format("%H:%M:%S"),
+            build_uptime_string(&stats.uptime, UptimeFormat::Normal),
+            format_users(stats.users),
+            format_loadavg(&stats.loadavg),
+            history.sparkline()
+        );
+        io::stdout().flush()?;
+
+        thread::sleep(StdDuration::from_secs(interval_secs));
+    }
 }
 
 #[derive(Debug)]
@@ -63,6 +257,29 @@ impl fmt::Display for ArgsError {
 }
 impl Error for ArgsError {}
 
+#[derive(Debug)]
+struct UtmpError;
+
+impl fmt::Display for UtmpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No BOOT_TIME record found in utmp file")
+    }
+}
+impl Error for UtmpError {}
+
+#[derive(Debug)]
+struct IdleError;
+
+impl fmt::Display for IdleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Idle time is only available when reading from /proc/uptime, not from a file"
+        )
+    }
+}
+impl Error for IdleError {}
+
 #[derive(Debug, PartialEq)]
 enum UptimeFormat {
     Normal,
@@ -103,56 +320,73 @@ fn build_uptime_string(uptime: &Duration, kind: UptimeFormat) -> String {
     result
 }
 
-/// Get a Duration object with the uptime in seconds
-fn get_uptime(read_data: String) -> Result<Duration, Box<dyn Error>> {
-    let time_str: String = read_data.split_whitespace().take(1).collect();
+/// Reads /proc/uptime and returns the system uptime and the cumulative
+/// idle time summed across all CPUs, keeping sub-second precision
+fn get_uptime(read_data: String) -> Result<(Duration, Duration), Box<dyn Error>> {
+    let mut fields = read_data.split_whitespace();
+
+    let uptime = parse_seconds(fields.next().unwrap_or(""))?;
+    let idle = parse_seconds(fields.next().unwrap_or(""))?;
+
+    Ok((uptime, idle))
+}
+
+/// Parses a float number of seconds, as found in /proc/uptime, into a
+/// Duration with millisecond precision
+fn parse_seconds(value: &str) -> Result<Duration, Box<dyn Error>> {
+    let seconds = value.parse::<f64>()?;
 
-    // Conversion can fail, but Duration works with integers
-    let seconds = time_str.parse::<f64>()? as i64;
+    Ok(Duration::milliseconds((seconds * 1000.0).round() as i64))
+}
 
-    Ok(Duration::seconds(seconds))
+/// Counts the number of CPU cores listed in /proc/cpuinfo
+fn get_num_cpus(read_data: String) -> usize {
+    read_data
+        .lines()
+        .filter(|line| line.starts_with("processor"))
+        .count()
 }
 
-/// Reads /proc/loadavg and formats the result
-fn get_loadavg(read_data: String) -> String {
-    let load: Vec<String> = read_data
-        .split_whitespace()
-        .take(3)
-        .map(|x| x.to_string())
-        .collect();
+/// Parses the three load averages out of /proc/loadavg
+fn parse_loadavg(read_data: String) -> Result<[f64; 3], Box<dyn Error>> {
+    let mut fields = read_data.split_whitespace();
 
-    let load_str = format!("{}, {}, {}", load[0], load[1], load[2]);
+    let one = fields.next().unwrap_or("").parse::<f64>()?;
+    let five = fields.next().unwrap_or("").parse::<f64>()?;
+    let fifteen = fields.next().unwrap_or("").parse::<f64>()?;
 
-    load_str
+    Ok([one, five, fifteen])
+}
+
+/// Formats load averages the way `uptime` does
+fn format_loadavg(loadavg: &[f64; 3]) -> String {
+    format!("{:.2}, {:.2}, {:.2}", loadavg[0], loadavg[1], loadavg[2])
+}
+
+/// Formats a user count the way `uptime` does
+fn format_users(count: usize) -> String {
+    if count == 1 {
+        format!("{} user", count)
+    } else {
+        format!("{} users", count)
+    }
 }
 
 /// Print usage information
 fn usage() {
     println!("\nUsage:");
-    println!(" ruptime [option]\n");
+    println!(" ruptime [option] [FILE]\n");
 
     println!("Options:");
     println!(" -p, --pretty   show uptime in pretty format");
     println!(" -h, --help     display this help and exit");
     println!(" -s, --since    system up since");
-    println!(" -V, --version  output version information and exit\n");
+    println!(" -V, --version  output version information and exit");
+    println!(" -i, --idle     show idle time and average idle percentage");
+    println!(" --json         output a JSON-formatted status report");
+    println!(" --watch [SECONDS]  continuously re-sample and redraw the status");
+    println!("                    line with a load average sparkline (default 2s)");
+    println!(" --file FILE    read uptime and user data from a utmp/wtmp file");
+    println!("                instead of /proc/uptime and /var/run/utmp\n");
 }
 
-/// Get the number of logged users by reading the utmp file
-fn get_no_users(buf: &[u8]) -> String {
-    let mut count = 0;
-
-    for i in 0..(buf.len() / UTMP_SIZE) {
-        // At the start of each structure there is the type field
-        // 7 is the USER_PROCESS type
-        if buf[i * UTMP_SIZE] == 7 {
-            count += 1;
-        }
-    }
-
-    if count > 1 {
-        format!("{} users", count)
-    } else {
-        format!("{} user", count)
-    }
-}
@@ -0,0 +1,94 @@
+//! A fixed-size ring buffer of load-average samples and its sparkline
+//! rendering, used by `--watch` mode to show a trend at a glance
+const GLYPHS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A fixed-capacity window of recent load average samples
+pub struct LoadHistory {
+    samples: Vec<f64>,
+    capacity: usize,
+}
+
+impl LoadHistory {
+    pub fn new(capacity: usize) -> Self {
+        LoadHistory {
+            samples: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Pushes a new sample, dropping the oldest one once at capacity
+    pub fn push(&mut self, value: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.remove(0);
+        }
+        self.samples.push(value);
+    }
+
+    /// Renders the buffered samples as a sparkline, scaling each sample
+    /// against the running min/max of the window
+    pub fn sparkline(&self) -> String {
+        if self.samples.is_empty() {
+            return String::new();
+        }
+
+        let min = self.samples.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = self
+            .samples
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let range = max - min;
+
+        self.samples
+            .iter()
+            .map(|&value| {
+                let scaled = if range == 0.0 {
+                    0
+                } else {
+                    (((value - min) / range) * (GLYPHS.len() - 1) as f64).round() as usize
+                };
+                GLYPHS[scaled]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_drops_the_oldest_sample_past_capacity() {
+        let mut history = LoadHistory::new(2);
+        history.push(1.0);
+        history.push(2.0);
+        history.push(3.0);
+
+        assert_eq!(history.samples, vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn sparkline_is_empty_with_no_samples() {
+        let history = LoadHistory::new(4);
+
+        assert_eq!(history.sparkline(), "");
+    }
+
+    #[test]
+    fn sparkline_maps_min_and_max_to_the_outer_glyphs() {
+        let mut history = LoadHistory::new(4);
+        history.push(0.0);
+        history.push(1.0);
+
+        assert_eq!(history.sparkline(), "\u{2581}\u{2588}");
+    }
+
+    #[test]
+    fn sparkline_uses_the_lowest_glyph_when_all_samples_are_equal() {
+        let mut history = LoadHistory::new(4);
+        history.push(1.0);
+        history.push(1.0);
+
+        assert_eq!(history.sparkline(), "\u{2581}\u{2581}");
+    }
+}
@@ -0,0 +1,156 @@
+//! Parsing of utmp/wtmp login records
+use chrono::prelude::*;
+
+/// Size in bytes of one record in the glibc utmp/wtmp layout
+#[cfg(not(any(
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "macos"
+)))]
+const RECORD_SIZE: usize = 384;
+
+/// Size in bytes of one record in the classic BSD utmp layout
+#[cfg(any(
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "macos"
+))]
+const RECORD_SIZE: usize = 36;
+
+/// `ut_type` value for a process that represents a logged-in user
+const USER_PROCESS: i32 = 7;
+/// `ut_type` value for a system boot record
+const BOOT_TIME: i32 = 2;
+
+/// A single decoded utmp/wtmp record
+#[derive(Debug, Clone)]
+pub struct Utmp {
+    pub ut_type: i32,
+    pub ut_tv: i64,
+}
+
+/// Parses a raw utmp/wtmp buffer into a list of records, using the
+/// platform-appropriate layout
+pub fn parse(buf: &[u8]) -> Vec<Utmp> {
+    buf.chunks_exact(RECORD_SIZE).map(decode_record).collect()
+}
+
+/// Decodes one record using the glibc layout: 4-byte type, 4-byte pid,
+/// 32-byte line, 4-byte id, 32-byte user, 256-byte host, 4-byte exit,
+/// 4-byte session, then an 8-byte timeval starting at offset 340
+#[cfg(not(any(
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "macos"
+)))]
+fn decode_record(record: &[u8]) -> Utmp {
+    let ut_type = i32::from_ne_bytes(record[0..4].try_into().unwrap());
+    let tv_sec = i32::from_ne_bytes(record[340..344].try_into().unwrap());
+
+    Utmp {
+        ut_type,
+        ut_tv: tv_sec as i64,
+    }
+}
+
+/// Decodes one record using the classic BSD layout, which has no explicit
+/// type field and no BOOT_TIME marker: an empty user name means the slot is
+/// unused, otherwise it is treated as a USER_PROCESS record. Because there
+/// is nothing to tag a boot record with, `boot_time()` never finds one on
+/// these targets. `count_users()` still counts these records, but reading a
+/// file (`--file`, also under `--watch`) needs a boot record and so fails
+/// with `UtmpError` here before any users are counted
+#[cfg(any(
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "macos"
+))]
+fn decode_record(record: &[u8]) -> Utmp {
+    const UT_NAME_EMPTY: u8 = 0;
+
+    let ut_type = if record[8] == UT_NAME_EMPTY {
+        0
+    } else {
+        USER_PROCESS
+    };
+    let ut_time = i32::from_ne_bytes(record[32..36].try_into().unwrap());
+
+    Utmp {
+        ut_type,
+        ut_tv: ut_time as i64,
+    }
+}
+
+/// Counts the number of USER_PROCESS records
+pub fn count_users(records: &[Utmp]) -> usize {
+    records.iter().filter(|r| r.ut_type == USER_PROCESS).count()
+}
+
+/// Returns the boot time of the last BOOT_TIME record found, since a wtmp
+/// log can contain several boots and the most recent one reflects the
+/// current uptime
+pub fn boot_time(records: &[Utmp]) -> Option<DateTime<Local>> {
+    records
+        .iter()
+        .rev()
+        .find(|r| r.ut_type == BOOT_TIME)
+        .and_then(|r| Local.timestamp_opt(r.ut_tv, 0).single())
+}
+
+#[cfg(all(
+    test,
+    not(any(
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "macos"
+    ))
+))]
+mod tests {
+    use super::*;
+
+    /// Builds one glibc-layout record with the given `ut_type` and `ut_tv`
+    fn make_record(ut_type: i32, tv_sec: i32) -> Vec<u8> {
+        let mut record = vec![0u8; RECORD_SIZE];
+        record[0..4].copy_from_slice(&ut_type.to_ne_bytes());
+        record[340..344].copy_from_slice(&tv_sec.to_ne_bytes());
+        record
+    }
+
+    #[test]
+    fn counts_only_user_process_records() {
+        let mut buf = Vec::new();
+        buf.extend(make_record(BOOT_TIME, 1_000));
+        buf.extend(make_record(USER_PROCESS, 2_000));
+        buf.extend(make_record(0, 0));
+
+        let records = parse(&buf);
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(count_users(&records), 1);
+    }
+
+    #[test]
+    fn boot_time_returns_the_last_boot_record() {
+        let mut buf = Vec::new();
+        buf.extend(make_record(BOOT_TIME, 1_000));
+        buf.extend(make_record(BOOT_TIME, 5_000));
+
+        let records = parse(&buf);
+
+        assert_eq!(boot_time(&records).map(|dt| dt.timestamp()), Some(5_000));
+    }
+
+    #[test]
+    fn boot_time_is_none_without_a_boot_record() {
+        let buf = make_record(USER_PROCESS, 1_000);
+
+        let records = parse(&buf);
+
+        assert!(boot_time(&records).is_none());
+    }
+}